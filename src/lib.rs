@@ -1,8 +1,31 @@
 extern crate rand;
 
-use rand::random;
+use rand::Rng;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::distributions::{Distribution, WeightedIndex};
 use std::cmp::Ordering;
 
+/// Errors surfaced at the scheduling API boundary.
+#[derive(Debug, PartialEq)]
+pub enum StatfeedError {
+    /// Fewer options carry a positive weight than the number requested.
+    TooFewOptions,
+    /// A weight was negative or non-finite, or a criterion score matrix held a
+    /// non-positive or non-finite score or did not match the decision/option shape.
+    InvalidWeight,
+    /// A decision's weight row had no strictly positive weight to schedule.
+    AllWeightsZero,
+}
+
+/// One competing criterion in the weighted product model: a per-decision,
+/// per-option score matrix and the exponent that sets its importance relative
+/// to the other criteria.
+struct Criterion {
+    scores: Vec<Vec<f64>>,
+    importance: f64,
+}
+
 pub struct Statfeed<T> {
     pub randoms: Vec<Vec<f64>>,
     pub weights: Vec<Vec<f64>>,
@@ -11,19 +34,79 @@ pub struct Statfeed<T> {
     accents: Vec<f64>,
     statistics: Vec<f64>,
     decisions: Vec<usize>,
-    options: Vec<T>
+    options: Vec<T>,
+    constraints: Vec<Box<dyn Constraint<T>>>,
+    criteria: Vec<Criterion>
+}
+
+/// A rule that decides whether a candidate option may be chosen for a given
+/// decision, given the choices made so far. Constraints are consulted in
+/// `populate_choices`; an option is only picked if *every* registered
+/// constraint permits it, unless no option is permitted, in which case the
+/// constraints are relaxed for that decision.
+pub trait Constraint<T> {
+    fn permits(&self, candidate: &T, history: &[T], decision: usize) -> bool;
+}
+
+/// Forbid runs of the same option longer than `max`. A `max` of 0 disables
+/// the constraint.
+pub struct NoConsecutiveRepeats {
+    pub max: usize,
+}
+
+impl<T: PartialEq> Constraint<T> for NoConsecutiveRepeats {
+    fn permits(&self, candidate: &T, history: &[T], _decision: usize) -> bool {
+        if self.max == 0 {
+            return true;
+        }
+        let run = history.iter().rev().take_while(|h| *h == candidate).count();
+        run < self.max
+    }
+}
+
+/// Forbid picking a candidate that immediately follows a listed predecessor.
+pub struct ForbidTransitions<T> {
+    pub forbidden: Vec<(T, T)>,
+}
+
+impl<T: PartialEq> Constraint<T> for ForbidTransitions<T> {
+    fn permits(&self, candidate: &T, history: &[T], _decision: usize) -> bool {
+        match history.last() {
+            Some(prev) => !self.forbidden.iter().any(|(a, b)| a == prev && b == candidate),
+            None => true,
+        }
+    }
+}
+
+/// Require the candidate to be absent from the last `k` choices.
+pub struct AbsentFromLast {
+    pub k: usize,
+}
+
+impl<T: PartialEq> Constraint<T> for AbsentFromLast {
+    fn permits(&self, candidate: &T, history: &[T], _decision: usize) -> bool {
+        let start = history.len().saturating_sub(self.k);
+        !history[start..].iter().any(|h| h == candidate)
+    }
 }
 
 impl<T: Clone> Statfeed<T> {
     pub fn new(options: Vec<T>, size: usize) -> Self {
+        Self::from_rng(options, size, &mut rand::thread_rng())
+    }
+
+    /// Build a `Statfeed` whose noise is drawn from the supplied generator.
+    ///
+    /// Pass in your own `Rng` so a run can be reproduced bit-for-bit: the same
+    /// generator state yields the same `randoms`, and therefore the same
+    /// realization out of `populate_choices`.
+    pub fn from_rng<R: Rng>(options: Vec<T>, size: usize, rng: &mut R) -> Self {
         // Default to weights of 1.0 for each decision/option
         let weights: Vec<Vec<f64>> = (0..size).map(|_| {
             (0..options.len()).map(|_| 1.0 / options.len() as f64).collect()
         }).collect();
         // Generate random numbers
-        let randoms: Vec<Vec<f64>> = (0..size).map(|_| {
-            (0..options.len()).map(|_| random::<f64>()).collect()
-        }).collect();
+        let randoms = Self::generate_randoms(size, options.len(), rng);
         let heterogeneities = vec![0.1f64; size];
         let accents = vec![1f64; size];
         let statistics  = vec![0.0; options.len()];
@@ -31,33 +114,228 @@ impl<T: Clone> Statfeed<T> {
         let decisions: Vec<usize> = (0..size).collect();
 
         Statfeed {
-            weights: weights,
-            randoms: randoms,
-            heterogeneities: heterogeneities,
-            accents: accents,
-            statistics: statistics,
-            choices: choices,
-            decisions: decisions,
-            options: options
+            weights,
+            randoms,
+            heterogeneities,
+            accents,
+            statistics,
+            choices,
+            decisions,
+            options,
+            constraints: Vec::new(),
+            criteria: Vec::new()
         }
     }
 
-    pub fn populate_choices(&mut self) {
+    /// Register a constraint that every chosen option must satisfy.
+    pub fn add_constraint(&mut self, constraint: Box<dyn Constraint<T>>) {
+        self.constraints.push(constraint);
+    }
+
+    /// Register a criterion contributing a per-decision, per-option score
+    /// matrix with the given `importance` exponent. Effective weights are the
+    /// raw `weights` entry times the product over criteria of
+    /// `score.powf(importance)`; criteria scale the raw weights and do not
+    /// replace them.
+    ///
+    /// Scores must be strictly positive and finite; otherwise `powf` would
+    /// yield NaN or negative products, so invalid input is rejected up front.
+    pub fn add_criterion(&mut self, scores: Vec<Vec<f64>>, importance: f64) -> Result<(), StatfeedError> {
+        if scores.len() != self.decisions.len() {
+            return Err(StatfeedError::InvalidWeight);
+        }
+        for row in scores.iter() {
+            if row.len() != self.options.len() {
+                return Err(StatfeedError::InvalidWeight);
+            }
+            for &s in row.iter() {
+                if !s.is_finite() || s <= 0.0 {
+                    return Err(StatfeedError::InvalidWeight);
+                }
+            }
+        }
+        self.criteria.push(Criterion { scores, importance });
+        Ok(())
+    }
+
+    /// The effective weight for an option: the raw `weights` entry scaled by the
+    /// weighted product of every registered criterion's score.
+    ///
+    /// With no criteria the product is empty (1.0), so this is the raw weight
+    /// itself and the default scheduler's behaviour and ordering are unchanged.
+    /// A criterion reweights that base; the raw weight is always a factor.
+    fn effective_weight(&self, decision: usize, option: usize) -> f64 {
+        self.criteria.iter().fold(self.weights[decision][option], |acc, c| {
+            acc * c.scores[decision][option].powf(c.importance)
+        })
+    }
+
+    /// Seed a fresh `StdRng` from `seed` and build from it, for the common
+    /// case where the caller just wants a reproducible run from a single `u64`.
+    pub fn with_seed(options: Vec<T>, size: usize, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        Self::from_rng(options, size, &mut rng)
+    }
+
+    // Sample each noise value as a `f64` straight from the generator. `StdRng`
+    // produces the same stream on 32- and 64-bit targets, so a seeded run
+    // reproduces everywhere without any target-specific index juggling.
+    fn generate_randoms<R: Rng>(size: usize, options: usize, rng: &mut R) -> Vec<Vec<f64>> {
+        (0..size).map(|_| {
+            (0..options).map(|_| rng.gen::<f64>()).collect()
+        }).collect()
+    }
+
+    /// Re-draw the noise matrix from the supplied generator so `populate_choices`
+    /// can be replayed against a different realization of the same shape.
+    pub fn regenerate_randoms<R: Rng>(&mut self, rng: &mut R) {
+        let size = self.randoms.len();
+        self.randoms = Self::generate_randoms(size, self.options.len(), rng);
+    }
+
+    /// Reseed the noise deterministically from a single `u64`.
+    pub fn reseed(&mut self, seed: u64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        self.regenerate_randoms(&mut rng);
+    }
+
+    /// Check that every decision can be scheduled: each weight row must hold at
+    /// least one strictly positive, finite weight, and no weight may be negative
+    /// or non-finite. Catching this here keeps NaN out of `sort_options`; a
+    /// zero weight is allowed and gives its option an infinite scheduling value.
+    fn validate_weights(&self) -> Result<(), StatfeedError> {
+        for dec in 0..self.decisions.len() {
+            let mut any_positive = false;
+            for m in 0..self.options.len() {
+                let w = self.effective_weight(dec, m);
+                if !w.is_finite() || w < 0.0 {
+                    return Err(StatfeedError::InvalidWeight);
+                }
+                if w > 0.0 {
+                    any_positive = true;
+                }
+            }
+            if !any_positive {
+                return Err(StatfeedError::AllWeightsZero);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn populate_choices(&mut self) -> Result<(), StatfeedError> {
+        self.validate_weights()?;
         self.choices.clear();
         for dec in 0..self.decisions.len() {
             let (choice, index) = {
                 let options = self.sort_options(&self.scheduling_values(dec)[..]);
-                let index = options.iter().position(|el| self.is_acceptable(el, dec)).unwrap();
+                // Take the best-ranked option that satisfies every constraint.
+                // If the constraints rule out all of them, relax them and fall
+                // back to the top-ranked option rather than failing the run.
+                let index = options.iter().position(|el| self.is_acceptable(el, dec)).unwrap_or(0);
                 (options[index].clone(), index)
             };
             self.choices.push(choice);
             self.increment_statistics(dec, index);
             self.normalize_statistics(dec);
         }
+        Ok(())
+    }
+
+    /// Draw `k` *distinct* options per decision window, weighted by the
+    /// current weights, using the Efraimidis–Spirakis one-pass weighted
+    /// reservoir scheme (the algorithm behind rand's `choose_multiple_weighted`).
+    ///
+    /// Each positive-weight option `m` is assigned the key `u_m^(1/w_m)` for a
+    /// fresh `u_m ~ Uniform(0,1)`, and the `k` largest keys win. Zero-weight
+    /// options are skipped; the draw errors with `TooFewOptions` if a window has
+    /// fewer than `k` positive-weight options. Results are kept in descending
+    /// key order so callers receive a ranked draw.
+    pub fn populate_choices_without_replacement(&mut self, k: usize) -> Result<(), StatfeedError> {
+        self.validate_weights()?;
+        let mut rng = rand::thread_rng();
+        self.choices.clear();
+        for dec in 0..self.decisions.len() {
+            let ranked = self.weighted_keys_draw(dec, k, &mut rng)?;
+            for idx in ranked {
+                self.choices.push(self.options[idx].clone());
+            }
+        }
+        Ok(())
+    }
+
+    fn weighted_keys_draw<R: Rng>(&self, decision: usize, k: usize, rng: &mut R)
+        -> Result<Vec<usize>, StatfeedError>
+    {
+        let mut keyed: Vec<(usize, f64)> = (0..self.options.len())
+            .filter_map(|m| {
+                let w = self.effective_weight(decision, m);
+                if w > 0.0 {
+                    let u: f64 = rng.gen();
+                    Some((m, u.powf(1.0 / w)))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        if keyed.len() < k {
+            return Err(StatfeedError::TooFewOptions);
+        }
+        keyed.sort_by(|&(_, a), &(_, b)| b.partial_cmp(&a).unwrap_or(Ordering::Equal));
+        keyed.truncate(k);
+        Ok(keyed.into_iter().map(|(m, _)| m).collect())
+    }
+
+    /// Probabilistic counterpart to `populate_choices`.
+    ///
+    /// Instead of always taking the best-ranked acceptable option, turn the
+    /// scheduling values into a sampling distribution and draw from it. Lower
+    /// scheduling values are "more owed" a pick, so weights are a softmax over
+    /// the negated values, `w_m = exp(-(v_m - min_v) / temperature)`. A low
+    /// `temperature` approaches the deterministic scheduler; a high one spreads
+    /// the choice broadly across options.
+    pub fn populate_choices_stochastic<R: Rng>(&mut self, temperature: f64, rng: &mut R)
+        -> Result<(), StatfeedError>
+    {
+        self.validate_weights()?;
+        self.choices.clear();
+        for dec in 0..self.decisions.len() {
+            let index = {
+                let values = self.scheduling_values(dec);
+                let mut candidates: Vec<usize> = (0..self.options.len())
+                    .filter(|&m| self.is_acceptable(&self.options[m], dec))
+                    .collect();
+                // Relax to the full option set if nothing is acceptable, mirroring
+                // the fallback in `populate_choices`.
+                if candidates.is_empty() {
+                    candidates = (0..self.options.len()).collect();
+                }
+                let min_v = candidates.iter()
+                    .map(|&m| values[m])
+                    .fold(f64::INFINITY, f64::min);
+                let temp = if temperature > 0.0 { temperature } else { 1.0 };
+                let mut weights: Vec<f64> = candidates.iter()
+                    .map(|&m| (-(values[m] - min_v) / temp).exp())
+                    .collect();
+                // Guard against an all-zero distribution, which `WeightedIndex`
+                // rejects: fall back to a uniform draw over the candidates.
+                if weights.iter().all(|w| *w <= 0.0) {
+                    for w in weights.iter_mut() {
+                        *w = 1.0;
+                    }
+                }
+                let dist = WeightedIndex::new(&weights).unwrap();
+                candidates[dist.sample(rng)]
+            };
+            let choice = self.options[index].clone();
+            self.choices.push(choice);
+            self.increment_statistics(dec, index);
+            self.normalize_statistics(dec);
+        }
+        Ok(())
     }
 
-    fn is_acceptable(&self, el: &T, idx: usize) -> bool {
-        true
+    fn is_acceptable(&self, el: &T, decision: usize) -> bool {
+        self.constraints.iter().all(|c| c.permits(el, &self.choices, decision))
     }
 
     fn increment_statistics(&mut self, dec: usize, idx: usize) {
@@ -72,18 +350,21 @@ impl<T: Clone> Statfeed<T> {
         }
     }
 
-    fn normalization_value(&self, idx: usize) -> f64 {
-        self.accents[idx] / self.weights[idx].iter().fold(0., |a, v| a + v)
+    fn normalization_value(&self, decision: usize) -> f64 {
+        let total = (0..self.options.len())
+            .map(|m| self.effective_weight(decision, m))
+            .fold(0., |a, v| a + v);
+        self.accents[decision] / total
     }
 
     fn true_increment(&self, decision: usize, option: usize) -> f64 {
-        self.accents[decision] / self.weights[decision][option]
+        self.accents[decision] / self.effective_weight(decision, option)
     }
 
     fn expected_increment(&self, decision: usize, option: usize) -> f64 {
-        (self.accents[decision] + 
-         (self.heterogeneities[decision] * self.randoms[decision][option])) 
-        / self.weights[decision][option]
+        (self.accents[decision] +
+         (self.heterogeneities[decision] * self.randoms[decision][option]))
+        / self.effective_weight(decision, option)
     }
 
     fn scheduling_values(&self, decision: usize) -> Vec<f64> {
@@ -151,10 +432,102 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_with_seed_is_reproducible() {
+        let a = Statfeed::with_seed(vec!['a', 'b', 'c'], 3, 42);
+        let b = Statfeed::with_seed(vec!['a', 'b', 'c'], 3, 42);
+        assert_eq!(a.randoms, b.randoms);
+    }
+
+    #[test]
+    fn test_reseed_matches_with_seed() {
+        let mut sf = Statfeed::with_seed(vec!['a', 'b', 'c'], 3, 7);
+        let expected = Statfeed::with_seed(vec!['a', 'b', 'c'], 3, 99).randoms;
+        sf.reseed(99);
+        assert_eq!(expected, sf.randoms);
+    }
+
+    #[test]
+    fn test_absent_from_last_constraint() {
+        let mut sf = setup();
+        sf.add_constraint(Box::new(AbsentFromLast { k: 1 }));
+        sf.populate_choices().unwrap();
+        for pair in sf.choices.windows(2) {
+            assert_ne!(pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn test_add_criterion_rejects_non_positive_scores() {
+        let mut sf = setup();
+        let bad = vec![vec![1.0, 0.0, 1.0], vec![1.0, 1.0, 1.0], vec![1.0, 1.0, 1.0]];
+        assert_eq!(Err(StatfeedError::InvalidWeight), sf.add_criterion(bad, 1.0));
+    }
+
+    #[test]
+    fn test_add_criterion_rejects_wrong_shape() {
+        let mut sf = setup();
+        let short = vec![vec![1.0, 1.0, 1.0], vec![1.0, 1.0, 1.0]];
+        assert_eq!(Err(StatfeedError::InvalidWeight), sf.add_criterion(short, 1.0));
+        let narrow = vec![vec![1.0, 1.0], vec![1.0, 1.0], vec![1.0, 1.0]];
+        assert_eq!(Err(StatfeedError::InvalidWeight), sf.add_criterion(narrow, 1.0));
+    }
+
+    #[test]
+    fn test_criterion_drives_effective_weight() {
+        let mut sf = setup();
+        let scores = vec![vec![2.0, 1.0, 1.0], vec![1.0, 1.0, 1.0], vec![1.0, 1.0, 1.0]];
+        sf.add_criterion(scores, 1.0).unwrap();
+        // With a score of 2.0 for option 0 the effective weight doubles, so its
+        // true increment halves relative to the default of 3.0.
+        assert_in_delta(1.5, sf.true_increment(0, 0), 1.0e-10);
+    }
+
+    #[test]
+    fn test_without_replacement_draws_distinct() {
+        let mut sf = setup();
+        sf.populate_choices_without_replacement(2).unwrap();
+        assert_eq!(6, sf.choices.len());
+        for window in sf.choices.chunks(2) {
+            assert_ne!(window[0], window[1]);
+        }
+    }
+
+    #[test]
+    fn test_without_replacement_too_few_options() {
+        let mut sf = setup();
+        assert_eq!(Err(StatfeedError::TooFewOptions), sf.populate_choices_without_replacement(4));
+    }
+
+    #[test]
+    fn test_populate_choices_stochastic() {
+        let mut sf = setup();
+        let mut rng = StdRng::seed_from_u64(1);
+        sf.populate_choices_stochastic(0.5, &mut rng).unwrap();
+        assert_eq!(3, sf.choices.len());
+        for c in sf.choices.iter() {
+            assert!(['a', 'b', 'c'].contains(c));
+        }
+    }
+
+    #[test]
+    fn test_populate_choices_rejects_all_zero_weights() {
+        let mut sf = setup();
+        sf.weights = vec![vec![0.0, 0.0, 0.0], vec![1.0, 1.0, 1.0], vec![1.0, 1.0, 1.0]];
+        assert_eq!(Err(StatfeedError::AllWeightsZero), sf.populate_choices());
+    }
+
+    #[test]
+    fn test_populate_choices_rejects_negative_weight() {
+        let mut sf = setup();
+        sf.weights = vec![vec![-1.0, 1.0, 1.0], vec![1.0, 1.0, 1.0], vec![1.0, 1.0, 1.0]];
+        assert_eq!(Err(StatfeedError::InvalidWeight), sf.populate_choices());
+    }
+
     #[test]
     fn test_populate_choices() {
         let mut sf = setup();
-        sf.populate_choices();
+        sf.populate_choices().unwrap();
         println!("choices: {:?}", &sf.choices);
         assert_eq!(3, sf.choices.len());
         for (exp, res) in ['a', 'b', 'b'].iter().zip(sf.choices.iter()) {